@@ -0,0 +1,244 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Erasure coding for candidate data availability.
+//!
+//! Rather than have a single authority responsible for storing a candidate's
+//! block and extrinsic data, that data is split into `n` systematic
+//! Reed-Solomon chunks, one per availability guarantor, where `n` is the
+//! number of guarantors in the candidate's group. Any `reconstruction_threshold(n)`
+//! of those chunks are enough to reconstruct the original data, so the whole
+//! can be recovered even if most guarantors never serve their chunk.
+//!
+//! A Merkle tree is built over the hashes of the `n` chunks; its root is what
+//! a `CandidateReceipt` commits to, and every chunk carries a branch proving
+//! its inclusion under that root.
+
+use reed_solomon::ReedSolomon;
+use primitives::Hash;
+use primitives::hashing::blake2_256;
+
+/// The number of correct chunks, out of `n` total, that are sufficient to
+/// reconstruct the encoded data.
+pub fn reconstruction_threshold(n: usize) -> usize {
+	n / 3 + 1
+}
+
+/// A single erasure-coded chunk of candidate data, together with the Merkle
+/// branch proving its inclusion under a candidate's erasure root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+	/// The chunk's index among the `n` total chunks.
+	pub index: usize,
+	/// The raw chunk data.
+	pub data: Vec<u8>,
+	/// Merkle branch from this chunk's hash up to the root, one sibling hash
+	/// per level.
+	pub proof: Vec<Hash>,
+}
+
+/// Erasure-code `data` into `n` systematic chunks, returning the Merkle root
+/// committing to all of them alongside the chunks themselves.
+///
+/// Returns `None` if `n` is too small to admit a valid encoding.
+pub fn encode(data: &[u8], n: usize) -> Option<(Hash, Vec<Chunk>)> {
+	let k = reconstruction_threshold(n);
+	if k == 0 || n < k {
+		return None;
+	}
+
+	let shard_len = (data.len() + k - 1) / k.max(1);
+	let shard_len = shard_len.max(1);
+
+	let mut shards: Vec<Vec<u8>> = data.chunks(shard_len)
+		.map(|chunk| {
+			let mut shard = chunk.to_vec();
+			shard.resize(shard_len, 0);
+			shard
+		})
+		.collect();
+	shards.resize(k, vec![0; shard_len]);
+	shards.resize(n, vec![0; shard_len]);
+
+	// `k == n` (e.g. a lone availability guarantor, `n == 1`) means there are
+	// no parity shards to compute: the data shards themselves are already
+	// the complete set of chunks, and `ReedSolomon::new` rejects a
+	// `parity_shards` count of zero.
+	let parity_shards = n - k;
+	if parity_shards > 0 {
+		let rs = ReedSolomon::new(k, parity_shards).ok()?;
+		rs.encode(&mut shards).ok()?;
+	}
+
+	let hashes: Vec<Hash> = shards.iter().map(|shard| Hash(blake2_256(shard))).collect();
+	let (root, branches) = merkle_root_and_branches(&hashes);
+
+	let chunks = shards.into_iter().zip(branches.into_iter()).enumerate()
+		.map(|(index, (data, proof))| Chunk { index, data, proof })
+		.collect();
+
+	Some((root, chunks))
+}
+
+/// Reconstruct the original encoded data from at least
+/// `reconstruction_threshold(n)` of its `n` chunks, trimming to `original_len`.
+///
+/// Returns `None` if too few chunks are supplied or reconstruction fails.
+pub fn reconstruct(n: usize, chunks: Vec<Chunk>, original_len: usize) -> Option<Vec<u8>> {
+	let k = reconstruction_threshold(n);
+	if chunks.len() < k {
+		return None;
+	}
+
+	let shard_len = chunks.get(0)?.data.len();
+	let mut shards: Vec<Option<Vec<u8>>> = vec![None; n];
+	for chunk in chunks {
+		if chunk.index >= n || chunk.data.len() != shard_len {
+			return None;
+		}
+		shards[chunk.index] = Some(chunk.data);
+	}
+
+	let parity_shards = n - k;
+	if parity_shards > 0 {
+		let rs = ReedSolomon::new(k, parity_shards).ok()?;
+		rs.reconstruct(&mut shards).ok()?;
+	}
+
+	let mut data = Vec::with_capacity(shard_len * k);
+	for shard in shards.into_iter().take(k) {
+		data.extend(shard?);
+	}
+	data.truncate(original_len);
+
+	Some(data)
+}
+
+/// Verify that `chunk` is included under `root`, given a total of `n` chunks.
+pub fn verify_proof(root: &Hash, chunk: &Chunk, n: usize) -> bool {
+	if chunk.index >= n {
+		return false;
+	}
+
+	let mut hash = Hash(blake2_256(&chunk.data));
+	let mut index = chunk.index;
+
+	for sibling in &chunk.proof {
+		hash = if index % 2 == 0 {
+			hash_pair(&hash, sibling)
+		} else {
+			hash_pair(sibling, &hash)
+		};
+		index /= 2;
+	}
+
+	hash == *root
+}
+
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+	let mut buf = Vec::with_capacity(64);
+	buf.extend_from_slice(&left.0);
+	buf.extend_from_slice(&right.0);
+	Hash(blake2_256(&buf))
+}
+
+// Build a Merkle tree over `leaves`, padding each level by duplicating its
+// last node when necessary, and return the root alongside each leaf's branch.
+fn merkle_root_and_branches(leaves: &[Hash]) -> (Hash, Vec<Vec<Hash>>) {
+	let n = leaves.len();
+	let mut branches: Vec<Vec<Hash>> = vec![Vec::new(); n];
+
+	if n == 0 {
+		return (Hash::default(), branches);
+	}
+
+	let mut level = leaves.to_vec();
+	let mut positions: Vec<usize> = (0..n).collect();
+
+	while level.len() > 1 {
+		if level.len() % 2 == 1 {
+			level.push(*level.last().expect("level is non-empty; qed"));
+		}
+
+		for (leaf, pos) in positions.iter_mut().enumerate() {
+			let sibling_index = *pos ^ 1;
+			branches[leaf].push(level[sibling_index]);
+			*pos /= 2;
+		}
+
+		level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+	}
+
+	(level[0], branches)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip_for_various_n_and_data_lengths() {
+		let cases: &[(usize, Vec<u8>)] = &[
+			(1, b"a lone guarantor gets the whole candidate".to_vec()),
+			(2, b"two guarantors, one data shard and one parity shard".to_vec()),
+			(4, b"hello world, this is parachain candidate data!".to_vec()),
+			(5, b"a".to_vec()),
+			(7, b"exactly sixteen b".to_vec()),
+			(10, Vec::new()),
+			(3, (0u8..255).collect()),
+			(9, (0u8..200).collect()),
+		];
+
+		for (n, data) in cases {
+			let n = *n;
+			let (root, chunks) = encode(data, n).expect("encode should succeed");
+			assert_eq!(chunks.len(), n);
+
+			for chunk in &chunks {
+				assert!(verify_proof(&root, chunk, n), "chunk {} should verify under the root", chunk.index);
+			}
+
+			let k = reconstruction_threshold(n);
+			let subset: Vec<_> = chunks.into_iter().take(k).collect();
+			let reconstructed = reconstruct(n, subset, data.len()).expect("reconstruct should succeed from k chunks");
+			assert_eq!(&reconstructed, data);
+		}
+	}
+
+	#[test]
+	fn tampered_chunk_fails_verification() {
+		let data = b"some parachain block data and extrinsics".to_vec();
+		let n = 6;
+		let (root, mut chunks) = encode(&data, n).expect("encode should succeed");
+
+		let mut chunk = chunks.remove(0);
+		assert!(verify_proof(&root, &chunk, n));
+
+		chunk.data[0] ^= 0xff;
+		assert!(!verify_proof(&root, &chunk, n));
+	}
+
+	#[test]
+	fn too_few_chunks_cannot_reconstruct() {
+		let data = b"not enough chunks here".to_vec();
+		let n = 9;
+		let (_, chunks) = encode(&data, n).expect("encode should succeed");
+
+		let k = reconstruction_threshold(n);
+		let too_few: Vec<_> = chunks.into_iter().take(k - 1).collect();
+		assert!(reconstruct(n, too_few, data.len()).is_none());
+	}
+}
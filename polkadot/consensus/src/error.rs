@@ -16,6 +16,8 @@
 
 //! Errors that can occur during the consensus process.
 
+use polkadot_primitives::parachain::Id as ParaId;
+
 error_chain! {
 	links {
 		PolkadotApi(::polkadot_api::Error, ::polkadot_api::ErrorKind);
@@ -27,5 +29,10 @@ error_chain! {
 			description("Duty Roster had invalid length"),
 			display("Invalid duty roster length: expected {}, got {}", expected, got),
 		}
+
+		NoValidationCode(id: ParaId) {
+			description("Unable to find validation code for parachain"),
+			display("Unable to find validation code for parachain {:?}", id),
+		}
 	}
 }
@@ -33,6 +33,7 @@ extern crate futures;
 extern crate ed25519;
 extern crate parking_lot;
 extern crate tokio_timer;
+extern crate reed_solomon_erasure as reed_solomon;
 extern crate polkadot_api;
 extern crate polkadot_collator as collator;
 extern crate polkadot_statement_table as table;
@@ -46,23 +47,27 @@ extern crate error_chain;
 
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
 use codec::Slicable;
 use table::{Table, Context as TableContextTrait};
 use table::generic::Statement as GenericStatement;
 use polkadot_api::PolkadotApi;
 use polkadot_primitives::Hash;
-use polkadot_primitives::parachain::{Id as ParaId, DutyRoster, BlockData, Extrinsic, CandidateReceipt};
+use polkadot_primitives::parachain::{Id as ParaId, Chain, DutyRoster, BlockData, Extrinsic, CandidateReceipt};
 use primitives::block::{Block as SubstrateBlock, Header, HeaderHash, Id as BlockId};
 use primitives::AuthorityId;
 
 use futures::prelude::*;
 use futures::future;
+use futures::task::{self, Task};
 use parking_lot::Mutex;
 
 pub use self::error::{ErrorKind, Error};
+pub use self::availability::Chunk;
 
 mod error;
+mod availability;
 
 /// A handle to a statement table router.
 pub trait TableRouter {
@@ -84,13 +89,13 @@ pub trait TableRouter {
 }
 
 /// A long-lived network which can create statement table routing instances.
-pub trait Network {
+pub trait Network<C> {
 	/// The table router type. This should handle importing of any statements,
 	/// routing statements to peers, and driving completion of any `StatementProducers`.
 	type TableRouter: TableRouter;
 
 	/// Instantiate a table router.
-	fn table_router(&self, groups: HashMap<ParaId, GroupInfo>, table: Arc<SharedTable>) -> Self::TableRouter;
+	fn table_router(&self, groups: HashMap<ParaId, GroupInfo>, table: Arc<SharedTable<C>>) -> Self::TableRouter;
 }
 
 /// Information about a specific group.
@@ -134,6 +139,18 @@ impl TableContext {
 		self.key.public().0
 	}
 
+	// The number of availability guarantors in `group`, and this authority's
+	// deterministic position among them (used to pick an erasure-coded chunk).
+	fn local_availability_chunk(&self, group: &ParaId) -> Option<(usize, usize)> {
+		let group = self.groups.get(group)?;
+		let mut guarantors: Vec<_> = group.availability_guarantors.iter().cloned().collect();
+		guarantors.sort();
+
+		let local_id = self.local_id();
+		let index = guarantors.iter().position(|a| *a == local_id)?;
+		Some((guarantors.len(), index))
+	}
+
 	fn sign_statement(&self, statement: table::Statement) -> table::SignedStatement {
 		let signature = sign_table_statement(&statement, &self.key, &self.parent_hash);
 		let local_id = self.key.public().0;
@@ -150,6 +167,25 @@ impl TableContext {
 /// The actual message signed is the encoded statement concatenated with the
 /// parent hash.
 pub fn sign_table_statement(statement: &table::Statement, key: &ed25519::Pair, parent_hash: &Hash) -> ed25519::Signature {
+	let encoded = encode_statement_for_signing(statement, parent_hash);
+	key.sign(&encoded)
+}
+
+/// Verify a table statement's signature against a parent hash, using the same
+/// message encoding as `sign_table_statement`. This lets any third party
+/// verify a `SignedStatement` bundled into a misbehavior report without
+/// needing anything beyond the parent hash it was produced against.
+pub fn verify_table_statement(
+	statement: &table::Statement,
+	sender: &AuthorityId,
+	signature: &ed25519::Signature,
+	parent_hash: &Hash,
+) -> bool {
+	let encoded = encode_statement_for_signing(statement, parent_hash);
+	ed25519::Pair::verify(signature, &encoded, sender)
+}
+
+fn encode_statement_for_signing(statement: &table::Statement, parent_hash: &Hash) -> Vec<u8> {
 	use polkadot_primitives::parachain::Statement as RawStatement;
 
 	let raw = match *statement {
@@ -161,19 +197,43 @@ pub fn sign_table_statement(statement: &table::Statement, key: &ed25519::Pair, p
 
 	let mut encoded = raw.encode();
 	encoded.extend(&parent_hash.0);
+	encoded
+}
 
-	key.sign(&encoded)
+/// A self-contained proof of authority misbehavior: the offending authority,
+/// and the witnessed `table::Misbehavior` (which itself carries the
+/// conflicting signed statements). Verifiable by anyone holding only the
+/// parent hash it was produced against, via `verify_table_statement`.
+#[derive(Debug, Clone)]
+pub struct MisbehaviorReport {
+	/// The parent hash the conflicting statements were signed against.
+	pub parent_hash: Hash,
+	/// The authority being reported.
+	pub offender: AuthorityId,
+	/// The witnessed misbehavior.
+	pub proof: table::Misbehavior,
 }
 
 // A shared table object.
-struct SharedTableInner {
+struct SharedTableInner<C> {
 	table: Table<TableContext>,
 	proposed_digest: Option<Hash>,
 	checked_validity: HashSet<Hash>,
 	checked_availability: HashSet<Hash>,
+	// Offenders whose misbehavior has already been taken for inclusion in a
+	// proposed block. Tracking this here, rather than draining it out of
+	// `table` outright, lets a failed build hand the same offenders back
+	// without losing any misbehavior witnessed in the meantime.
+	reported_misbehavior: HashSet<AuthorityId>,
+	// Tasks parked waiting on a change to the table's includable candidates,
+	// e.g. `CreateProposal::poll`. Woken whenever a statement import changes
+	// the table's state, so a proposal doesn't have to wait out its full
+	// timeout once every group already has an includable candidate.
+	waiting_tasks: Vec<Task>,
+	api: Arc<C>,
 }
 
-impl SharedTableInner {
+impl<C: PolkadotApi> SharedTableInner<C> {
 	// Import a single statement. Provide a handle to a table router.
 	fn import_statement<R: TableRouter>(
 		&mut self,
@@ -181,11 +241,19 @@ impl SharedTableInner {
 		router: &R,
 		statement: table::SignedStatement,
 		received_from: Option<AuthorityId>,
-	) -> StatementProducer<<R::FetchCandidate as IntoFuture>::Future, <R::FetchExtrinsic as IntoFuture>::Future> {
+	) -> StatementProducer<<R::FetchCandidate as IntoFuture>::Future, <R::FetchExtrinsic as IntoFuture>::Future, C> {
 		let mut producer = StatementProducer {
 			fetch_block_data: None,
 			fetch_extrinsic: None,
 			produced_statements: Default::default(),
+			validity_candidate: None,
+			availability_candidate: None,
+			block_data: None,
+			extrinsic: None,
+			guarantor_count: 0,
+			local_chunk_index: 0,
+			parent_hash: context.parent_hash,
+			api: self.api.clone(),
 			_key: context.key.clone(),
 		};
 
@@ -194,6 +262,12 @@ impl SharedTableInner {
 			None => return producer,
 		};
 
+		// the table's candidate votes just changed, which may have made a
+		// new candidate includable; wake anyone waiting on that.
+		for task in self.waiting_tasks.drain(..) {
+			task.notify();
+		}
+
 		let local_id = context.local_id();
 		let is_validity_member = context.is_member_of(&local_id, &summary.group_id);
 		let is_availability_member =
@@ -214,11 +288,23 @@ impl SharedTableInner {
 				None => {} // TODO: handle table inconsistency somehow?
 				Some(candidate) => {
 					if checking_validity {
-						producer.fetch_block_data = Some(router.fetch_block_data(candidate).into_future().fuse());
+						producer.validity_candidate = Some(candidate.clone());
 					}
 
 					if checking_availability {
-						producer.fetch_extrinsic = Some(router.fetch_extrinsic_data(candidate).into_future().fuse());
+						if let Some((n, index)) = context.local_availability_chunk(&summary.group_id) {
+							producer.availability_candidate = Some(candidate.clone());
+							producer.guarantor_count = n;
+							producer.local_chunk_index = index;
+							producer.fetch_extrinsic = Some(router.fetch_extrinsic_data(candidate).into_future().fuse());
+						}
+					}
+
+					// availability is checked over the candidate's block and
+					// extrinsic data together, so fetch the block data whenever
+					// either check needs it.
+					if producer.validity_candidate.is_some() || producer.availability_candidate.is_some() {
+						producer.fetch_block_data = Some(router.fetch_block_data(candidate).into_future().fuse());
 					}
 				}
 			}
@@ -229,72 +315,165 @@ impl SharedTableInner {
 }
 
 /// Produced statements about a specific candidate.
-/// Both may be `None`.
+/// Any of the fields may be `None`.
 #[derive(Default)]
 pub struct ProducedStatements {
 	/// A statement about the validity of the candidate.
 	pub validity: Option<table::Statement>,
 	/// A statement about the availability of the candidate.
 	pub availability: Option<table::Statement>,
+	/// This authority's own erasure-coded chunk of the candidate's data,
+	/// produced alongside an `availability` statement so it can be served
+	/// to other guarantors.
+	pub local_chunk: Option<availability::Chunk>,
+	/// Set if validation couldn't be attempted at all (e.g. no validation
+	/// code could be found for the candidate's parachain), rather than being
+	/// run and rejecting the candidate outright. The `validity` statement
+	/// above is still conservatively set to `Invalid` in this case.
+	pub validation_error: Option<Error>,
 }
 
 /// Future that produces statements about a specific candidate.
-pub struct StatementProducer<D: Future, E: Future> {
+pub struct StatementProducer<D: Future, E: Future, C> {
 	fetch_block_data: Option<future::Fuse<D>>,
 	fetch_extrinsic: Option<future::Fuse<E>>,
 	produced_statements: ProducedStatements,
+	validity_candidate: Option<CandidateReceipt>,
+	availability_candidate: Option<CandidateReceipt>,
+	block_data: Option<BlockData>,
+	extrinsic: Option<Extrinsic>,
+	guarantor_count: usize,
+	local_chunk_index: usize,
+	parent_hash: Hash,
+	api: Arc<C>,
 	_key: Arc<ed25519::Pair>,
 }
 
-impl<D, E, Err> Future for StatementProducer<D, E>
+impl<D, E, C> StatementProducer<D, E, C>
+	where
+		D: Future<Item=BlockData>,
+		E: Future<Item=Extrinsic>,
+		C: PolkadotApi,
+{
+	// Run the parachain validation function against the fetched block data
+	// and check the result against what the candidate receipt claims.
+	//
+	// Errs if validation couldn't be attempted at all, e.g. because no
+	// validation code could be found for this candidate's parachain.
+	fn check_validity(&self, candidate: &CandidateReceipt, block_data: &BlockData) -> Result<bool, Error> {
+		let code = self.api.parachain_code(&BlockId::Hash(self.parent_hash), candidate.parachain_index)?
+			.ok_or_else(|| ErrorKind::NoValidationCode(candidate.parachain_index))?;
+
+		Ok(collator::validate_candidate(&code, candidate, block_data).unwrap_or(false))
+	}
+
+	// Erasure-code the candidate's block and extrinsic data, and check that
+	// our own chunk verifies against the root the receipt committed to.
+	fn check_availability(&self, candidate: &CandidateReceipt, block_data: &BlockData, extrinsic: &Extrinsic) -> Option<availability::Chunk> {
+		let mut data = block_data.encode();
+		data.extend(extrinsic.encode());
+
+		let (root, chunks) = availability::encode(&data, self.guarantor_count)?;
+		if root != candidate.erasure_root {
+			return None;
+		}
+
+		let chunk = chunks.into_iter().nth(self.local_chunk_index)?;
+		if availability::verify_proof(&root, &chunk, self.guarantor_count) {
+			Some(chunk)
+		} else {
+			None
+		}
+	}
+
+	// Produce an availability statement once both the block and extrinsic
+	// data for the candidate we're guaranteeing availability for have arrived.
+	fn try_produce_availability(&mut self) {
+		let (block_data, extrinsic) = match (self.block_data.clone(), self.extrinsic.clone()) {
+			(Some(b), Some(e)) => (b, e),
+			_ => return,
+		};
+
+		let candidate = match self.availability_candidate.take() {
+			Some(candidate) => candidate,
+			None => return,
+		};
+
+		if let Some(chunk) = self.check_availability(&candidate, &block_data, &extrinsic) {
+			self.produced_statements.availability = Some(GenericStatement::Available(candidate.hash()));
+			self.produced_statements.local_chunk = Some(chunk);
+		}
+	}
+}
+
+impl<D, E, C, Err> Future for StatementProducer<D, E, C>
 	where
 		D: Future<Item=BlockData,Error=Err>,
 		E: Future<Item=Extrinsic,Error=Err>,
+		C: PolkadotApi,
 {
 	type Item = ProducedStatements;
 	type Error = Err;
 
 	fn poll(&mut self) -> Poll<ProducedStatements, Err> {
-		let mut done = true;
-		if let Some(ref mut fetch_block_data) = self.fetch_block_data {
-			match fetch_block_data.poll()? {
-				Async::Ready(_block_data) => {
-					// TODO: validate block data here.
-					unimplemented!();
-				},
-				Async::NotReady => {
-					done = false;
+		let block_data_poll = match self.fetch_block_data {
+			Some(ref mut fetch_block_data) => Some(fetch_block_data.poll()?),
+			None => None,
+		};
+
+		match block_data_poll {
+			Some(Async::Ready(block_data)) => {
+				self.fetch_block_data = None;
+				self.block_data = Some(block_data.clone());
+
+				if let Some(candidate) = self.validity_candidate.take() {
+					let statement = match self.check_validity(&candidate, &block_data) {
+						Ok(true) => GenericStatement::Valid(candidate.hash()),
+						Ok(false) => GenericStatement::Invalid(candidate.hash()),
+						Err(e) => {
+							self.produced_statements.validation_error = Some(e);
+							GenericStatement::Invalid(candidate.hash())
+						}
+					};
+
+					self.produced_statements.validity = Some(statement);
 				}
+
+				self.try_produce_availability();
 			}
+			Some(Async::NotReady) | None => {}
 		}
 
-		if let Some(ref mut fetch_extrinsic) = self.fetch_extrinsic {
-			match fetch_extrinsic.poll()? {
-				Async::Ready(_extrinsic) => {
-					// TODO: guarantee availability of data.
-					unimplemented!();
-				}
-				Async::NotReady => {
-					done = false;
-				}
+		let extrinsic_poll = match self.fetch_extrinsic {
+			Some(ref mut fetch_extrinsic) => Some(fetch_extrinsic.poll()?),
+			None => None,
+		};
+
+		match extrinsic_poll {
+			Some(Async::Ready(extrinsic)) => {
+				self.fetch_extrinsic = None;
+				self.extrinsic = Some(extrinsic);
+
+				self.try_produce_availability();
 			}
+			Some(Async::NotReady) | None => {}
 		}
 
-		if done {
-			Ok(Async::Ready(::std::mem::replace(&mut self.produced_statements, Default::default())))
-		} else {
+		if self.fetch_block_data.is_some() || self.fetch_extrinsic.is_some() {
 			Ok(Async::NotReady)
+		} else {
+			Ok(Async::Ready(::std::mem::replace(&mut self.produced_statements, Default::default())))
 		}
 	}
 }
 
 /// A shared table object.
-pub struct SharedTable {
+pub struct SharedTable<C> {
 	context: Arc<TableContext>,
-	inner: Arc<Mutex<SharedTableInner>>,
+	inner: Arc<Mutex<SharedTableInner<C>>>,
 }
 
-impl Clone for SharedTable {
+impl<C> Clone for SharedTable<C> {
 	fn clone(&self) -> Self {
 		SharedTable {
 			context: self.context.clone(),
@@ -303,12 +482,13 @@ impl Clone for SharedTable {
 	}
 }
 
-impl SharedTable {
+impl<C: PolkadotApi> SharedTable<C> {
 	/// Create a new shared table.
 	///
-	/// Provide the key to sign with, and the parent hash of the relay chain
-	/// block being built.
-	pub fn new(groups: HashMap<ParaId, GroupInfo>, key: Arc<ed25519::Pair>, parent_hash: Hash) -> Self {
+	/// Provide the key to sign with, the parent hash of the relay chain
+	/// block being built, and an API client for looking up parachain
+	/// validation code.
+	pub fn new(groups: HashMap<ParaId, GroupInfo>, key: Arc<ed25519::Pair>, parent_hash: Hash, api: Arc<C>) -> Self {
 		SharedTable {
 			context: Arc::new(TableContext { groups, key, parent_hash }),
 			inner: Arc::new(Mutex::new(SharedTableInner {
@@ -316,6 +496,9 @@ impl SharedTable {
 				proposed_digest: None,
 				checked_validity: HashSet::new(),
 				checked_availability: HashSet::new(),
+				reported_misbehavior: HashSet::new(),
+				waiting_tasks: Vec::new(),
+				api,
 			}))
 		}
 	}
@@ -327,7 +510,7 @@ impl SharedTable {
 		router: &R,
 		statement: table::SignedStatement,
 		received_from: Option<AuthorityId>,
-	) -> StatementProducer<<R::FetchCandidate as IntoFuture>::Future, <R::FetchExtrinsic as IntoFuture>::Future> {
+	) -> StatementProducer<<R::FetchCandidate as IntoFuture>::Future, <R::FetchExtrinsic as IntoFuture>::Future, C> {
 		self.inner.lock().import_statement(&*self.context, router, statement, received_from)
 	}
 
@@ -336,7 +519,7 @@ impl SharedTable {
 		&self,
 		router: &R,
 		statement: table::Statement,
-	) -> StatementProducer<<R::FetchCandidate as IntoFuture>::Future, <R::FetchExtrinsic as IntoFuture>::Future> {
+	) -> StatementProducer<<R::FetchCandidate as IntoFuture>::Future, <R::FetchExtrinsic as IntoFuture>::Future, C> {
 		let proposed_digest = match statement {
 			GenericStatement::Candidate(ref c) => Some(c.hash()),
 			_ => None,
@@ -361,7 +544,8 @@ impl SharedTable {
 			I: IntoIterator<Item=(table::SignedStatement, Option<AuthorityId>)>,
 			U: ::std::iter::FromIterator<StatementProducer<
 				<R::FetchCandidate as IntoFuture>::Future,
-				<R::FetchExtrinsic as IntoFuture>::Future>
+				<R::FetchExtrinsic as IntoFuture>::Future,
+				C>
 			>,
 	{
 		let mut inner = self.inner.lock();
@@ -371,9 +555,52 @@ impl SharedTable {
 		}).collect()
 	}
 
+	/// Candidates which have crossed the validity and availability thresholds
+	/// for their group, ordered deterministically by `ParaId` so that every
+	/// authority builds the same extrinsic ordering from the same table state.
+	pub fn includable_candidates(&self) -> Vec<CandidateReceipt> {
+		let inner = self.inner.lock();
+		let mut candidates = inner.table.includable_candidates(&*self.context);
+		candidates.sort_by_key(|c| c.parachain_index);
+		candidates
+	}
+
+	/// Whether every parachain group has an includable candidate.
+	pub fn all_groups_have_candidate(&self) -> bool {
+		let candidates = self.includable_candidates();
+		self.context.groups.keys().all(|id| candidates.iter().any(|c| c.parachain_index == *id))
+	}
+
 	/// Check if a proposal is valid.
-	pub fn proposal_valid(&self, _proposal: &SubstrateBlock) -> bool {
-		false // TODO
+	///
+	/// Decodes the candidate-inclusion extrinsics the proposal claims to
+	/// build on top of `parent_hash`, then checks that every included
+	/// candidate names a known parachain group with no duplicates, and that
+	/// each one has crossed this table's validity and availability
+	/// thresholds with no recorded invalidity. A proposal is not required to
+	/// include a candidate for every group: `CreateProposal` may propose with
+	/// only the candidates that became includable before its timeout fired,
+	/// and such partial proposals must still be able to pass evaluation.
+	pub fn proposal_valid(&self, proposal: &SubstrateBlock) -> bool {
+		let api = self.inner.lock().api.clone();
+
+		let candidates = match api.check_parachain_proposal(&BlockId::Hash(self.context.parent_hash), proposal) {
+			Ok(candidates) => candidates,
+			Err(_) => return false,
+		};
+
+		let mut seen = HashSet::new();
+		for candidate in &candidates {
+			if !self.context.groups.contains_key(&candidate.parachain_index) {
+				return false;
+			}
+			if !seen.insert(candidate.parachain_index) {
+				return false;
+			}
+		}
+
+		let includable = self.includable_candidates();
+		candidates.iter().all(|candidate| includable.iter().any(|c| c.hash() == candidate.hash()))
 	}
 
 	/// Execute a closure using a specific candidate.
@@ -391,6 +618,41 @@ impl SharedTable {
 		self.inner.lock().table.get_misbehavior().clone()
 	}
 
+	/// Atomically take all witnessed misbehavior that hasn't already been
+	/// taken, as self-contained, independently verifiable reports, marking
+	/// each offender reported in the same lock acquisition. This ensures
+	/// misbehavior witnessed concurrently with a take can never be silently
+	/// dropped: it simply isn't part of the returned snapshot, and remains
+	/// available to a later call.
+	///
+	/// If the reports end up not being used (e.g. the block embedding them
+	/// failed to build), pass them to `untake_misbehavior_reports` so they
+	/// aren't lost.
+	pub fn take_misbehavior_reports(&self) -> Vec<MisbehaviorReport> {
+		let parent_hash = self.context.parent_hash;
+		let mut inner = self.inner.lock();
+		let reports: Vec<_> = inner.table.get_misbehavior().iter()
+			.filter(|(offender, _)| !inner.reported_misbehavior.contains(offender))
+			.map(|(offender, proof)| MisbehaviorReport { parent_hash, offender: offender.clone(), proof: proof.clone() })
+			.collect();
+
+		for report in &reports {
+			inner.reported_misbehavior.insert(report.offender.clone());
+		}
+
+		reports
+	}
+
+	/// Un-mark previously taken misbehavior reports, so they are picked up
+	/// again by a later call to `take_misbehavior_reports`. Call this if a
+	/// block meant to embed them failed to build.
+	pub fn untake_misbehavior_reports(&self, reports: &[MisbehaviorReport]) {
+		let mut inner = self.inner.lock();
+		for report in reports {
+			inner.reported_misbehavior.remove(&report.offender);
+		}
+	}
+
 	/// Fill a statement batch.
 	pub fn fill_batch<B: table::StatementBatch>(&self, batch: &mut B) {
 		self.inner.lock().table.fill_batch(batch);
@@ -400,6 +662,15 @@ impl SharedTable {
 	pub fn proposed_hash(&self) -> Option<Hash> {
 		self.inner.lock().proposed_digest.clone()
 	}
+
+	/// Park the current task to be woken up the next time a statement
+	/// import changes the table's includable candidates. Intended to be
+	/// called from `Future::poll` right before returning `NotReady`, so
+	/// that a stalled proposal resolves as soon as the table catches up
+	/// instead of waiting out a full timeout.
+	pub fn park_current_task(&self) {
+		self.inner.lock().waiting_tasks.push(task::current());
+	}
 }
 
 /// Polkadot proposer factory.
@@ -408,9 +679,16 @@ pub struct ProposerFactory<C, N> {
 	pub client: Arc<C>,
 	/// The backing network handle.
 	pub network: N,
+	/// The maximum amount of time to wait for parachain candidates to become
+	/// includable before proposing a block with whatever is available.
+	pub propose_timeout: Duration,
+	/// Shared timer used to time out proposals. A single `Timer` is reused
+	/// across every block, rather than spinning up a fresh timer wheel per
+	/// proposal.
+	pub timer: tokio_timer::Timer,
 }
 
-fn make_group_info(roster: DutyRoster, authorities: &[AuthorityId]) -> Result<HashMap<ParaId, GroupInfo>, Error> {
+fn make_group_info(duty_roster: DutyRoster, authorities: &[AuthorityId]) -> Result<HashMap<ParaId, GroupInfo>, Error> {
 	if duty_roster.validator_duty.len() != authorities.len() {
 		bail!(ErrorKind::InvalidDutyRosterLength(authorities.len(), duty_roster.validator_duty.len()))
 	}
@@ -421,20 +699,56 @@ fn make_group_info(roster: DutyRoster, authorities: &[AuthorityId]) -> Result<Ha
 
 	let mut map = HashMap::new();
 
-	unimpleented!()
+	fn group_entry(map: &mut HashMap<ParaId, GroupInfo>, id: ParaId) -> &mut GroupInfo {
+		map.entry(id).or_insert_with(|| GroupInfo {
+			validity_guarantors: HashSet::new(),
+			availability_guarantors: HashSet::new(),
+			needed_validity: 0,
+			needed_availability: 0,
+		})
+	}
+
+	for (authority, duty) in authorities.iter().zip(&duty_roster.validator_duty) {
+		if let Chain::Parachain(id) = *duty {
+			group_entry(&mut map, id).validity_guarantors.insert(authority.clone());
+		}
+	}
+
+	for (authority, duty) in authorities.iter().zip(&duty_roster.guarantor_duty) {
+		if let Chain::Parachain(id) = *duty {
+			group_entry(&mut map, id).availability_guarantors.insert(authority.clone());
+		}
+	}
+
+	// a supermajority of each set of guarantors is needed to back a candidate.
+	for info in map.values_mut() {
+		info.needed_validity = info.validity_guarantors.len() * 2 / 3 + 1;
+		info.needed_availability = info.availability_guarantors.len() * 2 / 3 + 1;
+	}
+
+	Ok(map)
 }
 
-impl<C: PolkadotApi, N: Network> bft::ProposerFactory for ProposerFactory<C, N> {
+impl<C: PolkadotApi, N: Network<C>> bft::ProposerFactory for ProposerFactory<C, N> {
 	type Proposer = Proposer<C, N::TableRouter>;
 	type Error = Error;
 
 	fn init(&self, parent_header: &Header, authorities: &[AuthorityId], sign_with: Arc<ed25519::Pair>) -> Result<Self::Proposer, Error> {
 		let parent_hash = parent_header.hash();
 		let duty_roster = self.client.duty_roster(&BlockId::Hash(parent_hash))?;
-
-		make_group_info(duty_roster, authorities);
-
-		unimplemented!()
+		let groups = make_group_info(duty_roster, authorities)?;
+
+		let table = Arc::new(SharedTable::new(groups.clone(), sign_with, parent_hash, self.client.clone()));
+		let router = self.network.table_router(groups, table.clone());
+
+		Ok(Proposer {
+			parent_hash,
+			client: self.client.clone(),
+			table,
+			router,
+			propose_timeout: self.propose_timeout,
+			timer: self.timer.clone(),
+		})
 	}
 }
 
@@ -442,17 +756,72 @@ impl<C: PolkadotApi, N: Network> bft::ProposerFactory for ProposerFactory<C, N>
 pub struct Proposer<C, R> {
 	parent_hash: HeaderHash,
 	client: Arc<C>,
+	table: Arc<SharedTable<C>>,
 	router: R,
+	propose_timeout: Duration,
+	timer: tokio_timer::Timer,
 }
 
 impl<C: PolkadotApi, R: TableRouter> bft::Proposer for Proposer<C, R> {
-	type CreateProposal = Result<SubstrateBlock, bft::Error>;
+	type CreateProposal = CreateProposal<C>;
 
 	fn propose(&self) -> Self::CreateProposal {
-		unimplemented!()
+		CreateProposal {
+			parent_hash: self.parent_hash,
+			client: self.client.clone(),
+			table: self.table.clone(),
+			timeout: self.timer.sleep(self.propose_timeout),
+		}
 	}
 
 	fn evaluate(&self, proposal: &SubstrateBlock) -> bool {
-		unimplemented!()
+		self.table.proposal_valid(proposal)
+	}
+}
+
+/// Future which resolves to a proposed block once every parachain group has
+/// an includable candidate, or the configured timeout elapses, whichever
+/// comes first.
+pub struct CreateProposal<C> {
+	parent_hash: HeaderHash,
+	client: Arc<C>,
+	table: Arc<SharedTable<C>>,
+	timeout: tokio_timer::Sleep,
+}
+
+impl<C: PolkadotApi> Future for CreateProposal<C> {
+	type Item = SubstrateBlock;
+	type Error = bft::Error;
+
+	fn poll(&mut self) -> Poll<SubstrateBlock, bft::Error> {
+		let timed_out = match self.timeout.poll() {
+			Ok(Async::Ready(())) => true,
+			Ok(Async::NotReady) => false,
+			Err(_) => true,
+		};
+
+		if !timed_out && !self.table.all_groups_have_candidate() {
+			// Re-polled on the timeout firing, yes, but also as soon as a
+			// statement import makes a new candidate includable: park this
+			// task with the table so `SharedTableInner::import_statement`
+			// can wake it directly, rather than relying on the timeout (or
+			// the agreement loop's own unrelated re-polls) to ever notice.
+			self.table.park_current_task();
+			return Ok(Async::NotReady);
+		}
+
+		let candidates = self.table.includable_candidates();
+		let misbehavior = self.table.take_misbehavior_reports();
+		let block = match self.client.build_block(&BlockId::Hash(self.parent_hash), candidates, misbehavior.clone()) {
+			Ok(block) => block,
+			Err(_) => {
+				// the block never got built, so these reports were never
+				// embedded anywhere; make them available to try again.
+				self.table.untake_misbehavior_reports(&misbehavior);
+				return Err(bft::Error::from("failed to build proposed block"));
+			}
+		};
+
+		Ok(Async::Ready(block))
 	}
 }